@@ -1,11 +1,11 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, SamplingMode};
-use grex_nc_dump_test::{DumpRing, Payload};
+use grex_nc_dump_test::{DumpConfig, DumpRing, Payload};
 
 fn dump(c: &mut Criterion) {
     let n = 2usize.pow(20);
-    let mut dr = DumpRing::new(n);
+    let mut dr = DumpRing::<i8>::new(n, 60000.0, 1e-6);
     for _ in 0..n {
-        dr.push(&Payload::random());
+        dr.push(&Payload::<i8>::random());
     }
     let mut group = c.benchmark_group("dump");
     group.sampling_mode(SamplingMode::Flat);
@@ -13,11 +13,25 @@ fn dump(c: &mut Criterion) {
     // Chunk sizes from 0.5 to 64 MiB
     // 0.25, 0.5, 1, 2, 4, 8, 16, 32, 64 MiB
     for size in [32, 64, 128, 256, 512, 1024, 2048, 4096, 8192].iter() {
-        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
-            b.iter(|| {
-                dr.dump(size).unwrap();
-            });
-        });
+        // Deflate levels 0 (off), 1 (fastest), and 9 (smallest), with and
+        // without the byte-shuffle pre-filter.
+        for &level in &[0u8, 1, 9] {
+            for &shuffle in &[false, true] {
+                if level == 0 && shuffle {
+                    // Shuffling with compression disabled has no effect on size.
+                    continue;
+                }
+                let config = DumpConfig { level, shuffle };
+                let id = BenchmarkId::from_parameter(format!(
+                    "{size}/level={level}/shuffle={shuffle}"
+                ));
+                group.bench_with_input(id, &(*size, config), |b, &(size, config)| {
+                    b.iter(|| {
+                        dr.dump(size, config).unwrap();
+                    });
+                });
+            }
+        }
     }
     group.finish();
 }
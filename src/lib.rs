@@ -1,22 +1,89 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
 use color_eyre::eyre;
+use ndarray::azip;
 use ndarray::prelude::*;
 use num_complex::Complex;
 use rayon::prelude::*;
 
 const CHANNELS: usize = 2048;
 
-/// Payload as they come from the NIC
+/// Compression settings for [`DumpRing::dump`], [`DumpRing::dump_async`] and
+/// [`DumpRing::dump_window`].
+///
+/// `level` is the deflate level (0 disables compression entirely; 1-9 trade
+/// write speed for on-disk size) applied after chunking, which is already
+/// aligned to the time axis via `chunk_size`. `shuffle` enables HDF5's
+/// byte-shuffle filter, which regroups same-significance bytes of each
+/// element across a chunk before deflating; it only changes the data for
+/// multi-byte sample types (`i16`/`i32`/`f32`). With `i8` samples every
+/// element is a single byte, so the filter leaves the data as it is, and no
+/// bit-level shuffle is performed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DumpConfig {
+    pub level: u8,
+    pub shuffle: bool,
+}
+
+/// A single complex sample component that the ring (and the NetCDF dump it
+/// produces) can be generic over.
+///
+/// `i8` is the raw format emitted by the NIC; `i16`/`i32` cover requantized
+/// or calibrated fixed-point data, and `f32` covers post-processed float
+/// dumps. Implementing this for a new type requires that `netcdf` already
+/// knows how to put/get it (`NcPutGet`) and a [`Sample::to_f64`] widening.
+pub trait Sample: Copy + Default + Send + Sync + netcdf::NcPutGet + 'static {
+    /// Evaluated at monomorphization time: enforces that `Complex<Self>` is
+    /// laid out as a bare `[re, im]` pair, which is what
+    /// [`Payload::as_ndarray_data_view`]'s transmute depends on. Referencing
+    /// this associated const forces the compiler to check it for every `T`
+    /// the ring is instantiated with.
+    const LAYOUT_ASSERT: () = assert!(
+        std::mem::size_of::<Complex<Self>>() == 2 * std::mem::size_of::<Self>(),
+        "Complex<T> must be a bare [re, im] pair for the ndarray transmute to be sound"
+    );
+
+    /// Widen a single component to `f64` for power accumulation, where the
+    /// bit width of the raw sample no longer matters.
+    fn to_f64(self) -> f64;
+}
+
+impl Sample for i8 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+impl Sample for i16 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+impl Sample for i32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+impl Sample for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+/// Payload as they come from the NIC (or, once requantized/calibrated,
+/// whatever wider type the back-end hands us).
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
-pub struct Payload {
+pub struct Payload<T: Sample> {
     count: u64,
-    pol_a: [Complex<i8>; CHANNELS],
-    pol_b: [Complex<i8>; CHANNELS],
+    pol_a: [Complex<T>; CHANNELS],
+    pol_b: [Complex<T>; CHANNELS],
 }
 
-impl Payload {
+impl<T: Sample> Payload<T> {
     /// Yields an [`ndarray::ArrayView3`] of dimensions (Polarization, Channel, Real/Imaginary)
-    fn as_ndarray_data_view(&self) -> ArrayView3<i8> {
+    fn as_ndarray_data_view(&self) -> ArrayView3<T> {
+        let () = T::LAYOUT_ASSERT;
         // C-array format, so the pol_a, pol_b chunk is in memory as
         //        POL A               POL B
         //  CH1   CH2   CH3  ...  CH1   CH2   CH3
@@ -30,12 +97,29 @@ impl Payload {
         // - It is safe to .offset() the pointer repeatedely along all axes (it's all bytes)
         // - The stides are non-negative
         // - The product of the non-zero axis lenghts (2*CHANNELS*2) does not exceed isize::MAX
+        // - T::LAYOUT_ASSERT guarantees [re, im] is exactly two T's back to back
         unsafe { ArrayView::from_shape_ptr((2, CHANNELS, 2), std::mem::transmute(raw_ptr)) }
     }
 
+    /// Total power per channel across both polarizations:
+    /// `pol_a.re² + pol_a.im² + pol_b.re² + pol_b.im²`. Feeds the
+    /// round-robin power archives without needing the voltage ndarray view.
+    fn channel_power(&self) -> Array1<f64> {
+        Array1::from_shape_fn(CHANNELS, |c| {
+            let a = self.pol_a[c];
+            let b = self.pol_b[c];
+            a.re.to_f64().powi(2) + a.im.to_f64().powi(2) + b.re.to_f64().powi(2) + b.im.to_f64().powi(2)
+        })
+    }
+}
+
+impl<T: Sample> Payload<T>
+where
+    rand::distributions::Standard: rand::distributions::Distribution<T>,
+{
     pub fn random() -> Self {
-        let mut pol_a = [Default::default(); CHANNELS];
-        let mut pol_b = [Default::default(); CHANNELS];
+        let mut pol_a = [Complex::<T>::default(); CHANNELS];
+        let mut pol_b = [Complex::<T>::default(); CHANNELS];
 
         pol_a.par_iter_mut().for_each(|x| *x = rand::random());
         pol_b.par_iter_mut().for_each(|x| *x = rand::random());
@@ -48,42 +132,291 @@ impl Payload {
     }
 }
 
+/// The consolidation step (in base-rate samples) of each round-robin power
+/// archive, coarsest-last. Modeled on a typical RRDtool RRA ladder: a 1x
+/// archive at the native sample period, then 64x and 4096x archives that
+/// trade time resolution for a much longer baseline.
+const POWER_ARCHIVE_STEPS: &[u64] = &[1, 64, 4096];
+
+/// Number of rows kept in every power archive, regardless of its step. Fixed
+/// across archives so the coarser ones cover proportionally more history.
+const POWER_ARCHIVE_ROWS: usize = 4096;
+
+/// One fixed-resolution step of the round-robin power archive: folds `step`
+/// base-rate [`Payload::channel_power`] samples into a single per-channel
+/// (avg power, max power) row, the same split RRDtool makes between the
+/// primary data point accumulator and the consolidated data point it
+/// eventually produces.
+///
+/// Consolidation windows align to *pushes*, not packet counts: every `step`
+/// calls to [`PowerArchive::accumulate`] folds exactly one row, regardless
+/// of the packet-count deltas [`TimeTable`] records between them. A row
+/// therefore always covers exactly `step` resident samples, but -- once
+/// dropped packets are in the picture -- not always `step` packet counts of
+/// wall-clock time. To keep the archive placeable in absolute time despite
+/// that, every row remembers the packet count its window started at, so a
+/// real per-row MJD can be derived the same way it is for the base-rate
+/// `time` coordinate.
+#[derive(Debug)]
+struct PowerArchive {
+    /// Base-rate samples folded into each row.
+    step: u64,
+    /// Circular buffer of consolidated rows, shape (rows, CHANNELS).
+    avg: Array2<f64>,
+    /// Circular buffer of consolidated rows, shape (rows, CHANNELS).
+    max: Array2<f64>,
+    /// Packet count of the first sample folded into each consolidated row.
+    start_count: Array1<u64>,
+    write_ptr: usize,
+    full: bool,
+    /// Running sum/max for the window currently being accumulated.
+    acc_sum: Array1<f64>,
+    acc_max: Array1<f64>,
+    acc_count: u64,
+    /// Packet count of the first sample folded into the in-progress window.
+    window_start: Option<u64>,
+}
+
+impl PowerArchive {
+    fn new(step: u64, rows: usize) -> Self {
+        Self {
+            step,
+            avg: Array2::zeros((rows, CHANNELS)),
+            max: Array2::zeros((rows, CHANNELS)),
+            start_count: Array1::zeros(rows),
+            write_ptr: 0,
+            full: false,
+            acc_sum: Array1::zeros(CHANNELS),
+            acc_max: Array1::zeros(CHANNELS),
+            acc_count: 0,
+            window_start: None,
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.avg.len_of(Axis(0))
+    }
+
+    /// Accumulate one base-rate sample's per-channel power (from the packet
+    /// with the given resident `count`), folding into a new row and
+    /// resetting once `step` samples have landed.
+    fn accumulate(&mut self, power: &Array1<f64>, count: u64) {
+        if self.window_start.is_none() {
+            self.window_start = Some(count);
+        }
+        self.acc_sum += power;
+        azip!((m in &mut self.acc_max, &p in power) *m = m.max(p));
+        self.acc_count += 1;
+
+        if self.acc_count == self.step {
+            self.avg
+                .row_mut(self.write_ptr)
+                .assign(&(&self.acc_sum / self.step as f64));
+            self.max.row_mut(self.write_ptr).assign(&self.acc_max);
+            self.start_count[self.write_ptr] = self.window_start.take().unwrap();
+
+            self.acc_sum.fill(0.0);
+            self.acc_max.fill(0.0);
+            self.acc_count = 0;
+
+            let rows = self.rows();
+            self.write_ptr = (self.write_ptr + 1) % rows;
+            if self.write_ptr == 0 {
+                self.full = true;
+            }
+        }
+    }
+
+    /// Time-ordered, consecutive chunks of the `avg`/`max`/`start_count`
+    /// archives, same wraparound convention as [`DumpRing::consecutive_views`].
+    #[allow(clippy::type_complexity)]
+    fn consecutive_rows(
+        &self,
+    ) -> (
+        (ArrayView2<f64>, ArrayView2<f64>),
+        (ArrayView2<f64>, ArrayView2<f64>),
+        (ArrayView1<u64>, ArrayView1<u64>),
+    ) {
+        if !self.full {
+            (
+                (
+                    self.avg.slice(s![..self.write_ptr, ..]),
+                    ArrayView2::from_shape((0, CHANNELS), &[]).unwrap(),
+                ),
+                (
+                    self.max.slice(s![..self.write_ptr, ..]),
+                    ArrayView2::from_shape((0, CHANNELS), &[]).unwrap(),
+                ),
+                (
+                    self.start_count.slice(s![..self.write_ptr]),
+                    ArrayView1::from_shape(0, &[]).unwrap(),
+                ),
+            )
+        } else {
+            (
+                (
+                    self.avg.slice(s![self.write_ptr.., ..]),
+                    self.avg.slice(s![..self.write_ptr, ..]),
+                ),
+                (
+                    self.max.slice(s![self.write_ptr.., ..]),
+                    self.max.slice(s![..self.write_ptr, ..]),
+                ),
+                (
+                    self.start_count.slice(s![self.write_ptr..]),
+                    self.start_count.slice(s![..self.write_ptr]),
+                ),
+            )
+        }
+    }
+}
+
+/// One run in the sample-timing table: `count` consecutive resident samples
+/// each separated from the previous one by `delta` packet counts.
+///
+/// Modeled on MP4's `stts` box: run-length encoding the deltas is far more
+/// compact than a per-sample timestamp, and keeps an occasional dropped
+/// packet from blowing the table up to one entry per sample.
+#[derive(Debug, Clone, Copy)]
+struct TimeRun {
+    count: u64,
+    delta: u64,
+}
+
+/// Packet-count deltas between consecutive resident samples, oldest-to-newest.
+/// Has exactly one fewer entries than there are resident samples (the oldest
+/// sample has no preceding delta) and evolves in lockstep with the ring: a
+/// push appends a delta, and an eviction (once the ring is full) pops one.
+#[derive(Debug, Default)]
+struct TimeTable {
+    runs: std::collections::VecDeque<TimeRun>,
+}
+
+impl TimeTable {
+    fn push_delta(&mut self, delta: u64) {
+        match self.runs.back_mut() {
+            Some(run) if run.delta == delta => run.count += 1,
+            _ => self.runs.push_back(TimeRun { count: 1, delta }),
+        }
+    }
+
+    /// Drop the delta belonging to the sample the ring just evicted,
+    /// returning it so the caller can advance `oldest` by the real gap
+    /// instead of assuming it was always 1.
+    fn pop_oldest(&mut self) -> u64 {
+        let run = self
+            .runs
+            .front_mut()
+            .expect("time table has one delta per resident sample past the first");
+        let delta = run.delta;
+        run.count -= 1;
+        if run.count == 0 {
+            self.runs.pop_front();
+        }
+        delta
+    }
+
+    fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.count as usize).sum()
+    }
+
+    /// Expand back into per-sample deltas, oldest-to-newest.
+    fn deltas(&self) -> impl Iterator<Item = u64> + '_ {
+        self.runs
+            .iter()
+            .flat_map(|run| std::iter::repeat(run.delta).take(run.count as usize))
+    }
+}
+
 /// The voltage dump ringbuffer
 #[derive(Debug)]
-pub struct DumpRing {
+pub struct DumpRing<T: Sample> {
     /// The next time index we write into
     write_ptr: usize,
     /// The data itself (heap allocated)
-    buffer: Array4<i8>,
+    buffer: Array4<T>,
     /// The number of time samples in this array
     capacity: usize,
     /// The timestamp (packet count) of the oldest sample (pointed to by read_ptr).
     /// None if the buffer is empty
     oldest: Option<u64>,
+    /// The packet count of the most recently pushed sample. None if the
+    /// buffer is empty. Used to compute the delta fed to `time_table`.
+    newest: Option<u64>,
+    /// Run-length encoded packet-count deltas between resident samples, so
+    /// gaps from dropped packets don't have to be assumed away when
+    /// reconstructing per-sample timestamps.
+    time_table: TimeTable,
     // If the buffer is completly full
     full: bool,
+    /// Held by whichever background writer (spawned by [`DumpRing::dump_async`])
+    /// is currently draining its snapshot to disk. Acquiring this lock before
+    /// starting a new background write serializes writers without making the
+    /// live ring wait on anything but the snapshot copy.
+    writer_lock: Arc<Mutex<()>>,
+    /// Round-robin power archives, one per entry in [`POWER_ARCHIVE_STEPS`],
+    /// coarsest-last.
+    power_archives: Vec<PowerArchive>,
+    /// MJD (TAI) timestamp of packet count 0.
+    epoch_mjd: f64,
+    /// Seconds spanned by one packet count increment.
+    sample_period_seconds: f64,
 }
 
-impl DumpRing {
-    pub fn new(capacity: usize) -> Self {
+impl<T: Sample> DumpRing<T> {
+    pub fn new(capacity: usize, epoch_mjd: f64, sample_period_seconds: f64) -> Self {
+        // With capacity 1, `push` wraps `write_ptr` back to 0 on the very
+        // first call, but that same call returns early (before `oldest` is
+        // set) without ever marking the ring `full`. The second push then
+        // appends a `time_table` delta without the eviction path popping
+        // one, permanently desyncing `oldest`/`time_table` from the ring's
+        // true one-slot contents -- reject this capacity rather than carry
+        // that desync.
+        assert!(
+            capacity >= 2,
+            "DumpRing capacity must be at least 2, got {capacity}"
+        );
         // Allocate all the memory for the array
-        let buffer = Array::zeros((capacity, 2, CHANNELS, 2));
+        let buffer = Array::from_elem((capacity, 2, CHANNELS, 2), T::default());
         Self {
             buffer,
             capacity,
             write_ptr: 0,
             full: false,
             oldest: None,
+            newest: None,
+            time_table: TimeTable::default(),
+            writer_lock: Arc::new(Mutex::new(())),
+            power_archives: POWER_ARCHIVE_STEPS
+                .iter()
+                .map(|&step| PowerArchive::new(step, POWER_ARCHIVE_ROWS))
+                .collect(),
+            epoch_mjd,
+            sample_period_seconds,
         }
     }
 
-    pub fn push(&mut self, pl: &Payload) {
+    pub fn push(&mut self, pl: &Payload<T>) {
         // Copy the data into the slice pointed to by the write_ptr
         let data_view = pl.as_ndarray_data_view();
         self.buffer
             .slice_mut(s![self.write_ptr, .., .., ..])
             .assign(&data_view);
 
+        // Feed every power archive with this sample's per-channel power
+        let power = pl.channel_power();
+        for archive in &mut self.power_archives {
+            archive.accumulate(&power, pl.count);
+        }
+
+        // Record the gap to the previous sample before updating `newest`,
+        // so the run-table reflects any dropped packets instead of assuming
+        // counts are always contiguous.
+        if let Some(newest) = self.newest {
+            self.time_table.push_delta(pl.count - newest);
+        }
+        self.newest = Some(pl.count);
+
         // Move the pointer
         self.write_ptr = (self.write_ptr + 1) % self.capacity;
         // If there was no data update the timeslot of the oldest data and increment the write_ptr
@@ -93,11 +426,12 @@ impl DumpRing {
             return;
         }
 
-        // If we're full, we overwrite old data
-        // which increments the payload count of old data by one
-        // as they are always monotonically increasing by one
+        // If we're full, we overwrite old data, which advances the oldest
+        // sample's packet count by the real gap to the next one (not always
+        // 1, if a packet was dropped)
         if self.full {
-            self.oldest = Some(self.oldest.unwrap() + 1);
+            let evicted_delta = self.time_table.pop_oldest();
+            self.oldest = Some(self.oldest.unwrap() + evicted_delta);
         }
 
         // If we wrapped around the first time, we are now full
@@ -106,9 +440,39 @@ impl DumpRing {
         }
     }
 
+    /// Packet count of every resident sample, oldest-to-newest, reconstructed
+    /// from `oldest` and the run-length encoded deltas in `time_table`.
+    fn sample_counts(&self) -> Vec<u64> {
+        let Some(oldest) = self.oldest else {
+            return Vec::new();
+        };
+        let mut counts = Vec::with_capacity(self.time_table.len() + 1);
+        counts.push(oldest);
+        let mut count = oldest;
+        for delta in self.time_table.deltas() {
+            count += delta;
+            counts.push(count);
+        }
+        counts
+    }
+
+    /// Convert resident packet counts into MJD (TAI) timestamps.
+    fn counts_to_mjds(&self, counts: &[u64]) -> Array1<f64> {
+        counts
+            .iter()
+            .map(|&count| self.epoch_mjd + (count as f64) * self.sample_period_seconds / 86400.0)
+            .collect()
+    }
+
+    /// MJD (TAI) timestamp of every resident sample, oldest-to-newest, in
+    /// the same order [`DumpRing::consecutive_views`] serializes them in.
+    fn sample_mjds(&self) -> Array1<f64> {
+        self.counts_to_mjds(&self.sample_counts())
+    }
+
     /// Get the two array views that represent the time-ordered, consecutive memory chunks of the ringbuffer.
     /// The first view will always have data in it, and the second view will be buffer_capacity - length(first_view)
-    fn consecutive_views(&self) -> (ArrayView4<i8>, ArrayView4<i8>) {
+    fn consecutive_views(&self) -> (ArrayView4<T>, ArrayView4<T>) {
         // There are four different cases
         // 1. the buffer is empty or
         // 2. The buffer has yet to be filled to capacity  (and we always start at index 0) so there's only really one chunk
@@ -127,53 +491,434 @@ impl DumpRing {
         }
     }
 
-    pub fn dump(&self, chunk_size: usize) -> eyre::Result<()> {
-        // Create a tmpfile for this dump, as that will be on the OS drive (probably),
-        // which should be faster storage than the result path
-        let tmp_path = std::env::temp_dir();
-        let tmp_file_path = tmp_path.join("test.nc");
-        let mut file = netcdf::create(tmp_file_path)?;
+    /// Same as [`DumpRing::consecutive_views`], but restricted to the
+    /// logical (time-ordered) index range `[start, start + len)`, handling
+    /// the wrap-around split the same way.
+    fn consecutive_views_range(&self, start: usize, len: usize) -> (ArrayView4<T>, ArrayView4<T>) {
+        let (a, b) = self.consecutive_views();
+        let a_len = a.len_of(Axis(0));
+        let end = start + len;
+        let empty = || ArrayView4::from_shape((0, 2, CHANNELS, 2), &[]).unwrap();
 
-        // Add the file dimensions
-        file.add_dimension("time", self.capacity)?;
-        file.add_dimension("pol", 2)?;
-        file.add_dimension("freq", CHANNELS)?;
-        file.add_dimension("reim", 2)?;
+        if end <= a_len {
+            (a.slice_move(s![start..end, .., .., ..]), empty())
+        } else if start >= a_len {
+            (b.slice_move(s![start - a_len..end - a_len, .., .., ..]), empty())
+        } else {
+            (
+                a.slice_move(s![start.., .., .., ..]),
+                b.slice_move(s![..end - a_len, .., .., ..]),
+            )
+        }
+    }
 
-        // Describe the dimensions
-        let mut mjd = file.add_variable::<f64>("time", &["time"])?;
-        mjd.put_attribute("units", "Days")?;
-        mjd.put_attribute("long_name", "TAI days since the MJD Epoch")?;
+    /// Resolve a trigger-centered window into a logical `(start, len)` range
+    /// over the resident, time-ordered samples: `pre` samples before and
+    /// `post` samples after whichever resident sample is at-or-after
+    /// `center_count`, clamped to whatever is actually present.
+    ///
+    /// Errors if `center_count` doesn't fall within the packet-count span of
+    /// the resident data at all, rather than silently returning an empty or
+    /// wildly-offset window.
+    fn resolve_window(
+        &self,
+        center_count: u64,
+        pre: usize,
+        post: usize,
+    ) -> eyre::Result<(usize, usize)> {
+        let counts = self.sample_counts();
+        let (Some(&oldest), Some(&newest)) = (counts.first(), counts.last()) else {
+            eyre::bail!("ring buffer is empty");
+        };
+        if center_count < oldest || center_count > newest {
+            eyre::bail!(
+                "trigger count {center_count} is outside the resident range {oldest}..={newest}"
+            );
+        }
 
-        let mut pol = file.add_string_variable("pol", &["pol"])?;
-        pol.put_attribute("long_name", "Polarization")?;
-        pol.put_string("a", 0)?;
-        pol.put_string("b", 1)?;
+        // Nearest resident sample at-or-after the trigger (counts can skip
+        // over `center_count` exactly if a packet was dropped).
+        let center_idx = counts.partition_point(|&c| c < center_count);
+        let start = center_idx.saturating_sub(pre);
+        let end = (center_idx + post + 1).min(counts.len());
+        Ok((start, end - start))
+    }
 
-        let mut freq = file.add_variable::<f64>("freq", &["freq"])?;
-        freq.put_attribute("units", "Megahertz")?;
-        freq.put_attribute("long_name", "Frequency")?;
+    /// Owned, time-ordered snapshot of every power archive's `avg`/`max` rows
+    /// and their per-row MJD timestamps, ready to hand to a writer
+    /// (background or otherwise).
+    fn power_archive_snapshot(&self) -> Vec<(u64, Array2<f64>, Array2<f64>, Array1<f64>)> {
+        self.power_archives
+            .iter()
+            .map(|archive| {
+                let ((avg_a, avg_b), (max_a, max_b), (count_a, count_b)) =
+                    archive.consecutive_rows();
+                let avg = ndarray::concatenate(Axis(0), &[avg_a, avg_b])
+                    .expect("archive chunks share the channel axis");
+                let max = ndarray::concatenate(Axis(0), &[max_a, max_b])
+                    .expect("archive chunks share the channel axis");
+                let counts = ndarray::concatenate(Axis(0), &[count_a, count_b])
+                    .expect("archive chunks share the row axis");
+                let mjds = self.counts_to_mjds(counts.as_slice().expect("contiguous"));
+                (archive.step, avg, max, mjds)
+            })
+            .collect()
+    }
 
-        let mut reim = file.add_string_variable("reim", &["reim"])?;
-        reim.put_attribute("long_name", "Complex")?;
-        reim.put_string("real", 0)?;
-        reim.put_string("imaginary", 1)?;
+    pub fn dump(&self, chunk_size: usize, config: DumpConfig) -> eyre::Result<()> {
+        let (a, b) = self.consecutive_views();
+        let archives = self.power_archive_snapshot();
+        let mjds = self.sample_mjds();
+        write_voltages(self.capacity, a, b, &archives, &mjds, chunk_size, config)
+    }
 
-        // Setup our data block
-        let mut voltages = file.add_variable::<i8>("voltages", &["time", "pol", "freq", "reim"])?;
-        voltages.put_attribute("long_name", "Channelized Voltages")?;
-        voltages.put_attribute("units", "Volts")?;
+    /// Non-blocking variant of [`DumpRing::dump`].
+    ///
+    /// The (potentially multi-second, 1 GiB+) NetCDF write is the slow part,
+    /// not the snapshot, so this copies [`DumpRing::consecutive_views`] into
+    /// an owned, heap-allocated buffer synchronously and hands that off to a
+    /// dedicated writer thread. By the time this call returns, `self` is free
+    /// to keep accepting [`DumpRing::push`]s while the previous snapshot
+    /// drains to disk in the background.
+    ///
+    /// If a previous `dump_async` write is still draining, the returned
+    /// writer thread blocks on it before starting its own write, so writers
+    /// never run concurrently and clobber each other's tmp file. This can
+    /// make the returned [`DumpHandle`] take longer to join than the write
+    /// itself, but it never blocks the caller of `dump_async`.
+    pub fn dump_async(&self, chunk_size: usize, config: DumpConfig) -> DumpHandle {
+        let (a, b) = self.consecutive_views();
+        let a = a.to_owned();
+        let b = b.to_owned();
+        let archives = self.power_archive_snapshot();
+        let mjds = self.sample_mjds();
+        let capacity = self.capacity;
+        let lock = Arc::clone(&self.writer_lock);
 
-        // Write to the file, one timestep at a time (chunking in pols, channels, and reim)
-        // We want chunk sizes of 64MB, which works out to 16384 time samples
-        voltages.set_chunking(&[chunk_size, 2, CHANNELS, 2])?;
-        //voltages.set_compression(0, true)?;
+        let inner = thread::spawn(move || {
+            let _guard = lock.lock().expect("writer lock poisoned");
+            write_voltages(
+                capacity,
+                a.view(),
+                b.view(),
+                &archives,
+                &mjds,
+                chunk_size,
+                config,
+            )
+        });
 
-        let (a, b) = self.consecutive_views();
-        let a_len = a.len_of(Axis(0));
-        voltages.put((..a_len, .., .., ..), a)?;
-        voltages.put((a_len.., .., .., ..), b)?;
+        DumpHandle { inner }
+    }
+
+    /// Dump only the samples around a trigger, instead of the whole ring:
+    /// `pre` samples before and `post` samples after whichever resident
+    /// sample is at-or-after packet count `center_count`, clamped to
+    /// whatever data is actually present.
+    ///
+    /// Errors if `center_count` falls entirely outside the resident
+    /// packet-count range. The `time` dimension is sized to the extracted
+    /// window rather than `capacity`, so the resulting file is small. Unlike
+    /// [`DumpRing::dump`], this does not include the power archives, since
+    /// those already cover the long baseline around any trigger.
+    pub fn dump_window(
+        &self,
+        center_count: u64,
+        pre: usize,
+        post: usize,
+        chunk_size: usize,
+        config: DumpConfig,
+    ) -> eyre::Result<()> {
+        let (start, len) = self.resolve_window(center_count, pre, post)?;
+        let (a, b) = self.consecutive_views_range(start, len);
+        let counts = self.sample_counts();
+        let mjds = self.counts_to_mjds(&counts[start..start + len]);
+        write_voltages(len, a, b, &[], &mjds, chunk_size, config)
+    }
+}
+
+/// The datagram-socket operations [`DumpRing::ingest_from`] needs, implemented
+/// for both [`std::net::UdpSocket`] and [`std::os::unix::net::UnixDatagram`]
+/// so the same ingest loop can be driven by a live NIC capture or a replayed
+/// recording fed over a UNIX socket.
+pub trait DatagramSocket {
+    fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize>;
+    fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> std::io::Result<()>;
+}
+
+impl DatagramSocket for std::net::UdpSocket {
+    fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        std::net::UdpSocket::recv(self, buf)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> std::io::Result<()> {
+        std::net::UdpSocket::set_read_timeout(self, timeout)
+    }
+}
+
+impl DatagramSocket for std::os::unix::net::UnixDatagram {
+    fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        std::os::unix::net::UnixDatagram::recv(self, buf)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> std::io::Result<()> {
+        std::os::unix::net::UnixDatagram::set_read_timeout(self, timeout)
+    }
+}
+
+/// Counters returned by [`DumpRing::ingest_from`], so a caller can log or
+/// alert on a running ingest loop.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IngestStats {
+    /// Frames received at exactly `size_of::<Payload<T>>()` bytes and pushed.
+    pub received: u64,
+    /// Frames dropped because they weren't exactly `size_of::<Payload<T>>()`
+    /// bytes (short, or long; an oversized datagram truncated to the receive
+    /// buffer size still lands here, since that buffer is always longer
+    /// than one frame).
+    pub dropped: u64,
+}
+
+impl<T: Sample> DumpRing<T> {
+    /// Fill the ring directly from a datagram stream instead of a driver
+    /// program calling [`DumpRing::push`] by hand: read raw, fixed-size
+    /// `Payload<T>` frames from `socket` and push each one, until `stop` is
+    /// set.
+    ///
+    /// `recv_buf_len` controls the receive buffer size and should be at
+    /// least `size_of::<Payload<T>>()`; any datagram that doesn't come back
+    /// at exactly that size is counted as dropped rather than partially
+    /// reinterpreted. Polls `stop` between reads using a short read timeout,
+    /// so a caller can flip it from another thread to end the loop.
+    ///
+    /// The actual receive buffer is always over-allocated by at least one
+    /// byte beyond `size_of::<Payload<T>>()`, regardless of `recv_buf_len`:
+    /// a buffer sized at exactly the frame length would let `recv` silently
+    /// truncate an oversized (corrupt) datagram down to `frame_len` bytes,
+    /// which would then pass the length check below as if it were valid.
+    pub fn ingest_from<S: DatagramSocket>(
+        &mut self,
+        socket: &S,
+        stop: &std::sync::atomic::AtomicBool,
+        recv_buf_len: usize,
+    ) -> eyre::Result<IngestStats> {
+        use std::sync::atomic::Ordering;
+
+        let frame_len = std::mem::size_of::<Payload<T>>();
+        let mut buf = vec![0u8; recv_buf_len.max(frame_len + 1)];
+        let mut stats = IngestStats::default();
+
+        socket.set_read_timeout(Some(std::time::Duration::from_millis(100)))?;
 
-        Ok(())
+        while !stop.load(Ordering::Relaxed) {
+            let n = match socket.recv(&mut buf) {
+                Ok(n) => n,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if n != frame_len {
+                stats.dropped += 1;
+                continue;
+            }
+
+            // Safety: `buf[..n]` is exactly `size_of::<Payload<T>>()` bytes
+            // read off the wire, and `Payload<T>` is `#[repr(C)]` over
+            // `Sample` types for which every bit pattern is a valid value,
+            // so reinterpreting it is sound regardless of alignment.
+            let pl: Payload<T> =
+                unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const Payload<T>) };
+            self.push(&pl);
+            stats.received += 1;
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Handle to a background NetCDF write spawned by [`DumpRing::dump_async`].
+///
+/// Dropping this without calling [`DumpHandle::join`] detaches the writer
+/// thread and silently discards any write error, same as any other detached
+/// `JoinHandle`.
+pub struct DumpHandle {
+    inner: thread::JoinHandle<eyre::Result<()>>,
+}
+
+impl DumpHandle {
+    /// Block until the background write finishes, surfacing any `eyre` error
+    /// that occurred while writing the NetCDF file.
+    pub fn join(self) -> eyre::Result<()> {
+        self.inner.join().expect("writer thread panicked")
+    }
+}
+
+/// Shared implementation behind [`DumpRing::dump`], [`DumpRing::dump_async`]
+/// and [`DumpRing::dump_window`]: writes two time-ordered chunks (from
+/// `consecutive_views` or `consecutive_views_range`) into a freshly created
+/// NetCDF file, with a `time` dimension sized to `capacity`. `dump` and
+/// `dump_async` pass the ring's capacity (not just the portion of the buffer
+/// that's actually been written); `dump_window` passes the window length.
+fn write_voltages<T: Sample>(
+    capacity: usize,
+    a: ArrayView4<T>,
+    b: ArrayView4<T>,
+    power_archives: &[(u64, Array2<f64>, Array2<f64>, Array1<f64>)],
+    mjds: &Array1<f64>,
+    chunk_size: usize,
+    config: DumpConfig,
+) -> eyre::Result<()> {
+    // Create a tmpfile for this dump, as that will be on the OS drive (probably),
+    // which should be faster storage than the result path
+    let tmp_path = std::env::temp_dir();
+    let tmp_file_path = tmp_path.join("test.nc");
+    let mut file = netcdf::create(tmp_file_path)?;
+
+    // Add the file dimensions
+    file.add_dimension("time", capacity)?;
+    file.add_dimension("pol", 2)?;
+    file.add_dimension("freq", CHANNELS)?;
+    file.add_dimension("reim", 2)?;
+
+    // Describe the dimensions
+    let mut mjd = file.add_variable::<f64>("time", &["time"])?;
+    mjd.put_attribute("units", "Days")?;
+    mjd.put_attribute("long_name", "TAI days since the MJD Epoch")?;
+    mjd.put((..mjds.len(),), mjds.view())?;
+
+    let mut pol = file.add_string_variable("pol", &["pol"])?;
+    pol.put_attribute("long_name", "Polarization")?;
+    pol.put_string("a", 0)?;
+    pol.put_string("b", 1)?;
+
+    let mut freq = file.add_variable::<f64>("freq", &["freq"])?;
+    freq.put_attribute("units", "Megahertz")?;
+    freq.put_attribute("long_name", "Frequency")?;
+
+    let mut reim = file.add_string_variable("reim", &["reim"])?;
+    reim.put_attribute("long_name", "Complex")?;
+    reim.put_string("real", 0)?;
+    reim.put_string("imaginary", 1)?;
+
+    // Setup our data block, typed to match the sample component stored in the ring
+    let mut voltages = file.add_variable::<T>("voltages", &["time", "pol", "freq", "reim"])?;
+    voltages.put_attribute("long_name", "Channelized Voltages")?;
+    voltages.put_attribute("units", "Volts")?;
+
+    // Write to the file as (up to) two bulk puts, chunked along time with whole
+    // pols, channels, and reim in every chunk. A 64 MiB chunk of i8 data works
+    // out to 8192 time samples (2 * CHANNELS * 2 bytes per sample), but
+    // a fixed dimension rejects a chunk longer than the dimension itself (e.g.
+    // a `dump_window` extraction smaller than the requested chunk size), so
+    // clamp to the `time` dimension length.
+    let time_chunk = chunk_size.min(capacity.max(1)).max(1);
+    voltages.set_chunking(&[time_chunk, 2, CHANNELS, 2])?;
+    voltages.set_compression(config.level.into(), config.shuffle)?;
+
+    let a_len = a.len_of(Axis(0));
+    voltages.put((..a_len, .., .., ..), a)?;
+    voltages.put((a_len.., .., .., ..), b)?;
+
+    // One (power_time, power_avg, power_max, power_count) group of variables
+    // per round-robin archive, each with its own time dimension sized to
+    // however many rows that archive has actually consolidated so far.
+    for (idx, (step, avg, max, mjds)) in power_archives.iter().enumerate() {
+        let rows = avg.len_of(Axis(0));
+        // A zero-row archive (a coarse step that hasn't consolidated its
+        // first window yet) would add a dimension of length 0, which netCDF
+        // reads back as NC_UNLIMITED rather than a real fixed dimension --
+        // just skip it until it has something to write.
+        if rows == 0 {
+            continue;
+        }
+
+        let time_dim = format!("power_time_{idx}");
+        file.add_dimension(&time_dim, rows)?;
+
+        // Coordinate variable so each row is placeable in absolute time,
+        // same convention as the base-rate "time" variable above -- this
+        // matters because a row always spans `step` resident samples but,
+        // once packets are dropped, not always `step` packet counts.
+        let mut power_time = file.add_variable::<f64>(&time_dim, &[&time_dim])?;
+        power_time.put_attribute("units", "Days")?;
+        power_time.put_attribute(
+            "long_name",
+            "TAI days since the MJD Epoch (start of each consolidation window)",
+        )?;
+        power_time.put((..rows,), mjds.view())?;
+
+        let mut power_avg =
+            file.add_variable::<f64>(&format!("power_avg_{idx}"), &[&time_dim, "freq"])?;
+        power_avg.put_attribute("long_name", "Average channel power")?;
+        power_avg.put_attribute("units", "Power (arbitrary units)")?;
+        power_avg.put_attribute("consolidation_step_samples", *step as i64)?;
+        power_avg.put((.., ..), avg.view())?;
+
+        let mut power_max =
+            file.add_variable::<f64>(&format!("power_max_{idx}"), &[&time_dim, "freq"])?;
+        power_max.put_attribute("long_name", "Peak channel power")?;
+        power_max.put_attribute("units", "Power (arbitrary units)")?;
+        power_max.put_attribute("consolidation_step_samples", *step as i64)?;
+        power_max.put((.., ..), max.view())?;
+
+        // Every row folds exactly `step` resident samples by construction,
+        // but emit it per-row rather than relying solely on the
+        // `consolidation_step_samples` attribute, since the request calls
+        // for the sample count to be a stored consolidation value alongside
+        // avg/max.
+        let mut power_count =
+            file.add_variable::<u64>(&format!("power_count_{idx}"), &[&time_dim])?;
+        power_count.put_attribute("long_name", "Resident samples folded into each row")?;
+        power_count.put((..rows,), Array1::from_elem(rows, *step).view())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `DumpConfig::shuffle` enables HDF5's built-in shuffle filter via
+    /// `voltages.set_compression(level, shuffle)`; exercise the actual write
+    /// path (not a standalone byte-transpose) and confirm it round-trips.
+    #[test]
+    fn dump_with_shuffle_round_trips() {
+        let capacity = 4;
+        let mut dr = DumpRing::<i8>::new(capacity, 60000.0, 1e-6);
+        let payloads: Vec<_> = (0..capacity)
+            .map(|_| {
+                let pl = Payload::<i8>::random();
+                dr.push(&pl);
+                pl
+            })
+            .collect();
+
+        dr.dump(
+            capacity,
+            DumpConfig {
+                level: 1,
+                shuffle: true,
+            },
+        )
+        .expect("dump with shuffle enabled");
+
+        let file =
+            netcdf::open(std::env::temp_dir().join("test.nc")).expect("reopen dumped file");
+        let voltages = file.variable("voltages").expect("voltages variable");
+        let written = voltages
+            .get::<i8, _>((.., .., .., ..))
+            .expect("read back voltages");
+
+        for (t, pl) in payloads.iter().enumerate() {
+            assert_eq!(written.slice(s![t, .., .., ..]), pl.as_ndarray_data_view());
+        }
     }
 }